@@ -14,17 +14,35 @@
 //! See [`man machine-id(5)`](https://www.freedesktop.org/software/systemd/man/machine-id.html)
 //! and [`man sd_id128_get_machine(3)`](https://www.freedesktop.org/software/systemd/man/sd_id128_get_machine_app_specific.html)
 //! for details.
+//!
+//! [`get_boot_app_specific()`] provides the same derivation for the boot ID
+//! defined in `/proc/sys/kernel/random/boot_id`, which is reset on every
+//! reboot instead of being stable for the lifetime of the installation;
+//! [`get_boot()`] returns that boot ID itself.
+//!
+//! While the underlying source is `/etc/machine-id` on Linux, this crate also
+//! works on other platforms and in environments without that file: it falls
+//! back to the OS-native equivalent (e.g. the Windows registry or macOS'
+//! `IOPlatformUUID`), and, failing that, to a random ID that is generated
+//! once and persisted to an app-owned file so it stays stable across calls.
 
 use std::{
     fmt::{Debug, Display},
     fs::read_to_string,
-    io,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::OnceLock,
 };
 
 use hmac_sha256::HMAC;
 use uuid::Uuid;
 
 const MACHINE_ID_PATH: &str = "/etc/machine-id";
+const BOOT_ID_PATH: &str = "/proc/sys/kernel/random/boot_id";
+const CMDLINE_PATH: &str = "/proc/cmdline";
+const CONTAINER_UUID_CMDLINE_PARAM: &str = "container_uuid";
+const DMI_PRODUCT_UUID_PATH: &str = "/sys/class/dmi/id/product_uuid";
+const DEVICETREE_VM_UUID_PATH: &str = "/proc/device-tree/vm,uuid";
 
 /// Generate an app-specific machine ID derived from the machine ID defined in
 /// `/etc/machine-id` and an application ID.
@@ -39,18 +57,381 @@ const MACHINE_ID_PATH: &str = "/etc/machine-id";
 /// and [`man sd_id128_get_machine(3)`](https://www.freedesktop.org/software/systemd/man/sd_id128_get_machine_app_specific.html)
 /// for details.
 pub fn get(app_id: Uuid) -> Result<Uuid, Error> {
-    let machine_id = machine_id()?;
+    let machine_id = machine_id_cached()?;
+    let hmac = HMAC::mac(app_id, machine_id);
+    let id =
+        Uuid::from_slice(&hmac[0..16]).expect("HMAC-SHA256 output is always at least 16 bytes");
+    let id = make_v4_uuid(id);
+    Ok(id)
+}
+
+/// Returns the base machine ID, caching it in a process-wide [`OnceLock`]
+/// after the first successful read.
+///
+/// The machine ID is constant for the lifetime of the process, so caching it
+/// avoids re-reading and re-parsing the underlying ID source on every call
+/// to [`get()`] — only the cheap HMAC step is repeated per `app_id`. This
+/// means the cache intentionally does not observe later edits to
+/// `/etc/machine-id` (or whichever source backed the cached value) made
+/// after the first call; restart the process to pick up such changes.
+///
+/// The returned ID is the same confidential base ID `get()` derives from, so
+/// treat it with the same care.
+pub fn machine_id_cached() -> Result<Uuid, Error> {
+    if let Some(id) = MACHINE_ID.get() {
+        return Ok(*id);
+    }
+    let id = machine_id()?;
+    Ok(*MACHINE_ID.get_or_init(|| id))
+}
+
+static MACHINE_ID: OnceLock<Uuid> = OnceLock::new();
+
+/// Like [`get()`], but lets you name extra sources of a stable machine ID to
+/// consult when the platform's native source (e.g. `/etc/machine-id` on
+/// Linux) is missing or empty, as systemd does on minimal or virtualized
+/// systems.
+///
+/// The resolution order is: the platform's native source first, then each of
+/// `fallbacks` in order — the first source that yields a valid UUID is used.
+/// Pass [`MachineIdSource::DEFAULT_FALLBACKS`] to mirror systemd's own
+/// fallback order.
+///
+/// If every source in `fallbacks` is also missing or empty, this falls back
+/// further to the same generated-and-persisted ID as [`get()`], so despite
+/// the `Result` return, it will not error out on a missing machine ID — it
+/// only errors if that generated ID can't be read or persisted either. If
+/// you need to detect "no stable native or configured ID available" instead
+/// of silently getting a generated one, check the sources yourself rather
+/// than relying on this function's `Err` case.
+pub fn get_with_sources(app_id: Uuid, fallbacks: &[MachineIdSource]) -> Result<Uuid, Error> {
+    let machine_id = machine_id_with_fallbacks(fallbacks)?;
     let hmac = HMAC::mac(app_id, machine_id);
-    let id = Uuid::from_slice(&hmac[0..16])?;
+    let id =
+        Uuid::from_slice(&hmac[0..16]).expect("HMAC-SHA256 output is always at least 16 bytes");
     let id = make_v4_uuid(id);
     Ok(id)
 }
 
+/// A source of a stable machine ID consulted by [`get_with_sources()`] when
+/// `/etc/machine-id` is missing or empty.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MachineIdSource {
+    /// The `container_uuid=` parameter on the kernel command line, set by
+    /// most container runtimes.
+    ContainerUuid,
+    /// The DMI/SMBIOS `product_uuid`, exposed by the kernel at
+    /// `/sys/class/dmi/id/product_uuid` on KVM and other DMI-capable
+    /// systems.
+    DmiProductUuid,
+    /// The devicetree `vm,uuid` property, set by some hypervisors on
+    /// systems without DMI.
+    DevicetreeVmUuid,
+}
+impl MachineIdSource {
+    /// The fallback order used by systemd itself: `container_uuid=`, the
+    /// DMI `product_uuid`, then the devicetree `vm,uuid` property.
+    pub const DEFAULT_FALLBACKS: &'static [Self] = &[
+        Self::ContainerUuid,
+        Self::DmiProductUuid,
+        Self::DevicetreeVmUuid,
+    ];
+
+    fn read(self) -> Result<Uuid, Error> {
+        match self {
+            Self::ContainerUuid => {
+                let cmdline = read_to_string(CMDLINE_PATH).map_err(|source| Error::Io {
+                    path: CMDLINE_PATH.to_string(),
+                    source,
+                })?;
+                let prefix = format!("{CONTAINER_UUID_CMDLINE_PARAM}=");
+                let value = cmdline
+                    .split_whitespace()
+                    .find_map(|param| param.strip_prefix(&prefix))
+                    .ok_or_else(|| Error::Io {
+                        path: CMDLINE_PATH.to_string(),
+                        source: io::Error::new(
+                            io::ErrorKind::NotFound,
+                            format!(
+                                "no {CONTAINER_UUID_CMDLINE_PARAM}= parameter on the kernel \
+                                    command line"
+                            ),
+                        ),
+                    })?;
+                Uuid::try_parse(value).map_err(|source| Error::InvalidId {
+                    path: CMDLINE_PATH.to_string(),
+                    source,
+                })
+            }
+            Self::DmiProductUuid => read_uuid_file(DMI_PRODUCT_UUID_PATH),
+            Self::DevicetreeVmUuid => read_uuid_file(DEVICETREE_VM_UUID_PATH),
+        }
+    }
+}
+
+/// Try the platform's native machine ID source first, then each of
+/// `fallbacks` in order, and only as a true last resort fall back to a
+/// generated, persisted ID.
+fn machine_id_with_fallbacks(fallbacks: &[MachineIdSource]) -> Result<Uuid, Error> {
+    if let Ok(id) = platform::native_machine_id() {
+        return Ok(id);
+    }
+
+    for source in fallbacks {
+        if let Ok(id) = source.read() {
+            return Ok(id);
+        }
+    }
+
+    generated_id()
+}
+
+fn read_uuid_file(path: impl AsRef<Path>) -> Result<Uuid, Error> {
+    let path = path.as_ref();
+    let contents = read_to_string(path).map_err(|source| Error::Io {
+        path: path.display().to_string(),
+        source,
+    })?;
+    Uuid::try_parse(contents.trim_end()).map_err(|source| Error::InvalidId {
+        path: path.display().to_string(),
+        source,
+    })
+}
+
+/// Generate a boot-specific ID derived from the ID defined in
+/// `/proc/sys/kernel/random/boot_id` and an application ID.
+///
+/// This is the boot-specific counterpart to [`get()`]: it is derived the same
+/// way, but since the boot ID is reset on every reboot, the resulting ID does
+/// not survive a restart either. This is useful for identifiers that should
+/// only be stable for the lifetime of the current boot.
+///
+/// This implementation is based on systemd's `sd_id128_get_boot_app_specific()`.
+pub fn get_boot_app_specific(app_id: Uuid) -> Result<Uuid, Error> {
+    let boot_id = boot_id()?;
+    let hmac = HMAC::mac(app_id, boot_id);
+    let id =
+        Uuid::from_slice(&hmac[0..16]).expect("HMAC-SHA256 output is always at least 16 bytes");
+    let id = make_v4_uuid(id);
+    Ok(id)
+}
+
+/// Read the boot ID defined in `/proc/sys/kernel/random/boot_id`.
+///
+/// Unlike the machine ID, the boot ID is randomly generated by the kernel on
+/// every boot, so this value is only stable until the next restart.
+///
+/// This implementation is based on systemd's `sd_id128_get_boot()`.
+pub fn get_boot() -> Result<Uuid, Error> {
+    boot_id()
+}
+
+/// Derive a stable, locally-administered MAC address from an app-specific
+/// machine ID.
+///
+/// This is useful for generating a stable MAC address per board/application
+/// instead of randomizing it on every boot, as done downstream in barebox.
+/// The first 6 bytes of [`get(app_id)`](get()) are used, with the
+/// multicast/group bit cleared and the locally-administered bit set, so the
+/// result is always a valid unicast, locally-administered Ethernet address.
+pub fn get_mac(app_id: Uuid) -> Result<[u8; 6], Error> {
+    let id = get(app_id)?;
+    let mut mac = [0u8; 6];
+    mac.copy_from_slice(&id.as_bytes()[0..6]);
+    mac[0] = (mac[0] & 0xFE) | 0x02;
+    Ok(mac)
+}
+
+/// A MAC address, formatted as lowercase, colon-separated hex octets (e.g.
+/// `"02:1a:2b:3c:4d:5e"`) for use in network configuration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MacAddress(pub [u8; 6]);
+impl Display for MacAddress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let [a, b, c, d, e, f6] = self.0;
+        write!(f, "{a:02x}:{b:02x}:{c:02x}:{d:02x}:{e:02x}:{f6:02x}")
+    }
+}
+impl From<[u8; 6]> for MacAddress {
+    fn from(mac: [u8; 6]) -> Self {
+        Self(mac)
+    }
+}
+
+/// Resolve the base machine ID for the current platform, falling back to a
+/// generated, persisted ID if the platform doesn't expose a native one.
 fn machine_id() -> Result<Uuid, Error> {
-    let id = Uuid::try_parse(read_to_string(MACHINE_ID_PATH)?.trim_end())?;
+    platform::native_machine_id().or_else(|_| generated_id())
+}
+
+fn boot_id() -> Result<Uuid, Error> {
+    read_uuid_file(BOOT_ID_PATH)
+}
+
+/// Platform-specific sources of a stable, OS-provided machine ID.
+mod platform {
+    use super::*;
+
+    #[cfg(target_os = "linux")]
+    pub(super) fn native_machine_id() -> Result<Uuid, Error> {
+        const DBUS_MACHINE_ID_PATH: &str = "/var/lib/dbus/machine-id";
+
+        read_uuid_file(MACHINE_ID_PATH)
+            .or_else(|primary_err| read_uuid_file(DBUS_MACHINE_ID_PATH).map_err(|_| primary_err))
+    }
+
+    #[cfg(target_os = "macos")]
+    pub(super) fn native_machine_id() -> Result<Uuid, Error> {
+        const SOURCE: &str = "ioreg -rd1 -c IOPlatformExpertDevice (IOPlatformUUID)";
+
+        let output = std::process::Command::new("ioreg")
+            .args(["-rd1", "-c", "IOPlatformExpertDevice"])
+            .output()
+            .map_err(|source| Error::Io {
+                path: SOURCE.to_string(),
+                source,
+            })?;
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let value = stdout
+            .lines()
+            .find_map(|line| line.split("IOPlatformUUID").nth(1))
+            .and_then(|rest| rest.split('"').nth(2))
+            .ok_or_else(|| Error::Io {
+                path: SOURCE.to_string(),
+                source: io::Error::new(io::ErrorKind::NotFound, "IOPlatformUUID not found"),
+            })?;
+        Uuid::try_parse(value).map_err(|source| Error::InvalidId {
+            path: SOURCE.to_string(),
+            source,
+        })
+    }
+
+    #[cfg(target_os = "windows")]
+    pub(super) fn native_machine_id() -> Result<Uuid, Error> {
+        const SOURCE: &str = r"HKLM\SOFTWARE\Microsoft\Cryptography\MachineGuid";
+
+        let output = std::process::Command::new("reg")
+            .args([
+                "query",
+                r"HKLM\SOFTWARE\Microsoft\Cryptography",
+                "/v",
+                "MachineGuid",
+            ])
+            .output()
+            .map_err(|source| Error::Io {
+                path: SOURCE.to_string(),
+                source,
+            })?;
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let value = stdout
+            .lines()
+            .find_map(|line| {
+                let mut fields = line.split_whitespace();
+                (fields.next()? == "MachineGuid" && fields.next()? == "REG_SZ")
+                    .then(|| fields.next())
+                    .flatten()
+            })
+            .ok_or_else(|| Error::Io {
+                path: SOURCE.to_string(),
+                source: io::Error::new(io::ErrorKind::NotFound, "MachineGuid not found"),
+            })?;
+        Uuid::try_parse(value).map_err(|source| Error::InvalidId {
+            path: SOURCE.to_string(),
+            source,
+        })
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+    pub(super) fn native_machine_id() -> Result<Uuid, Error> {
+        Err(Error::Io {
+            path: "<no native machine ID source>".to_string(),
+            source: io::Error::new(
+                io::ErrorKind::Unsupported,
+                "this platform has no native machine ID source",
+            ),
+        })
+    }
+}
+
+/// Path of the app-owned file used to persist a generated machine ID on
+/// platforms/systems that don't expose a native one.
+fn generated_id_path() -> PathBuf {
+    #[cfg(windows)]
+    {
+        let program_data =
+            std::env::var_os("ProgramData").unwrap_or_else(|| r"C:\ProgramData".into());
+        PathBuf::from(program_data)
+            .join("app-machine-id")
+            .join("id")
+    }
+    #[cfg(not(windows))]
+    {
+        PathBuf::from("/var/lib/app-machine-id/id")
+    }
+}
+
+/// Read the persisted generated ID, or generate and persist a new one if
+/// none exists yet. Subsequent calls on the same system return the same ID.
+///
+/// The file is created with `O_CREAT | O_EXCL` so that concurrent first
+/// calls can't race each other into persisting (and returning) different
+/// IDs: whichever process loses the create re-reads the file that's now
+/// there.
+fn generated_id() -> Result<Uuid, Error> {
+    let path = generated_id_path();
+
+    if let Ok(id) = read_uuid_file(&path) {
+        return Ok(id);
+    }
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(|source| Error::Io {
+            path: path.display().to_string(),
+            source,
+        })?;
+    }
+
+    let id = match std::fs::OpenOptions::new()
+        .create_new(true)
+        .write(true)
+        .open(&path)
+    {
+        Ok(mut file) => {
+            let id = random_v4_uuid();
+            file.write_all(id.to_string().as_bytes())
+                .map_err(|source| Error::Io {
+                    path: path.display().to_string(),
+                    source,
+                })?;
+            id
+        }
+        Err(err) if err.kind() == io::ErrorKind::AlreadyExists => read_uuid_file(&path)?,
+        Err(source) => {
+            return Err(Error::Io {
+                path: path.display().to_string(),
+                source,
+            })
+        }
+    };
+
     Ok(id)
 }
 
+/// Generate a random UUIDv4 to seed [`generated_id()`].
+fn random_v4_uuid() -> Uuid {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    let seed = format!("{}-{nanos}", std::process::id());
+    let hash = HMAC::mac(seed, nanos.to_be_bytes());
+    let id =
+        Uuid::from_slice(&hash[0..16]).expect("HMAC-SHA256 output is always at least 16 bytes");
+    make_v4_uuid(id)
+}
+
 /// Turn the ID into a valid UUIDv4.
 ///
 /// This code is inspired by `generate_random_uuid()` of drivers/char/random.c from the Linux kernel sources.
@@ -66,21 +447,21 @@ fn make_v4_uuid(id: Uuid) -> Uuid {
 }
 
 #[derive(Debug)]
-/// Returned when reading the machine ID fails.
+/// Returned when reading the machine or boot ID fails.
 pub enum Error {
-    /// Could not read `/etc/machine-id`.
-    Io(io::Error),
-    /// The machine ID doesn't match the machine-id(5) format.
-    InvalidId(uuid::Error),
+    /// Could not read the ID source at `path`.
+    Io { path: String, source: io::Error },
+    /// The ID read from `path` doesn't match the machine-id(5) format.
+    InvalidId { path: String, source: uuid::Error },
 }
 impl Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Self::Io(err) => write!(f, "Could not read {MACHINE_ID_PATH}: {err}"),
-            Self::InvalidId(_) => {
+            Self::Io { path, source } => write!(f, "Could not read {path}: {source}"),
+            Self::InvalidId { path, .. } => {
                 write!(
                     f,
-                    "The machine ID in {MACHINE_ID_PATH} does not \
+                    "The ID in {path} does not \
                         match the format descibed in machine-id(5)"
                 )
             }
@@ -90,21 +471,11 @@ impl Display for Error {
 impl std::error::Error for Error {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         Some(match self {
-            Self::Io(err) => err,
-            Self::InvalidId(err) => err,
+            Self::Io { source, .. } => source,
+            Self::InvalidId { source, .. } => source,
         })
     }
 }
-impl From<io::Error> for Error {
-    fn from(err: io::Error) -> Self {
-        Self::Io(err)
-    }
-}
-impl From<uuid::Error> for Error {
-    fn from(err: uuid::Error) -> Self {
-        Self::InvalidId(err)
-    }
-}
 
 #[cfg(test)]
 mod tests {
@@ -139,4 +510,58 @@ mod tests {
         let this = get(APP_ID).unwrap();
         assert_eq!(systemd, this);
     }
+
+    #[test]
+    fn test_boot_id() {
+        let systemd = Uuid::try_parse_ascii(
+            &std::process::Command::new("systemd-id128")
+                .args(["boot-id"])
+                .output()
+                .unwrap()
+                .stdout[0..32],
+        )
+        .unwrap();
+        let this = boot_id().unwrap();
+        assert_eq!(systemd, this);
+    }
+
+    #[test]
+    fn test_app_specific_boot_id() {
+        let systemd = Uuid::try_parse_ascii(
+            &std::process::Command::new("systemd-id128")
+                .args(["boot-id", "--app-specific", &APP_ID.to_string()])
+                .output()
+                .unwrap()
+                .stdout[0..32],
+        )
+        .unwrap();
+        let this = get_boot_app_specific(APP_ID).unwrap();
+        assert_eq!(systemd, this);
+    }
+
+    #[test]
+    fn test_machine_id_cached_matches_machine_id() {
+        let this = machine_id().unwrap();
+        let cached = machine_id_cached().unwrap();
+        assert_eq!(this, cached);
+    }
+
+    #[test]
+    fn test_get_with_sources_uses_machine_id_when_present() {
+        let this = get(APP_ID).unwrap();
+        let with_sources = get_with_sources(APP_ID, MachineIdSource::DEFAULT_FALLBACKS).unwrap();
+        assert_eq!(this, with_sources);
+    }
+
+    #[test]
+    fn test_get_mac_is_unicast_and_locally_administered() {
+        let mac = get_mac(APP_ID).unwrap();
+        assert_eq!(mac[0] & 0x01, 0, "multicast/group bit must be cleared");
+        assert_eq!(mac[0] & 0x02, 0x02, "locally-administered bit must be set");
+        assert_eq!(
+            MacAddress(mac).to_string().len(),
+            17,
+            "colon-separated hex should be 17 characters"
+        );
+    }
 }